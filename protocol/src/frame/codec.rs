@@ -0,0 +1,122 @@
+//! A [`tokio_util::codec`] codec wrapping the nom parsers and the cookie-factory
+//! generators, so the crate can be plugged directly into a [`Framed`] transport.
+//!
+//! [`Framed`]: tokio_util::codec::Framed
+
+use crate::frame::*;
+use bytes::{Buf, BufMut, BytesMut};
+use cookie_factory::{gen_simple, GenError};
+use std::{fmt, io};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// A codec turning a byte stream into [`AMQPFrame`]s and back.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AMQPCodec;
+
+/// An error raised while decoding from or encoding to the wire.
+#[derive(Debug)]
+pub enum AMQPCodecError {
+    /// The bytes on the wire could not be parsed as a valid frame
+    Parse(String),
+    /// A frame could not be serialized
+    Generate(GenError),
+    /// An underlying I/O error occurred
+    Io(io::Error),
+}
+
+impl fmt::Display for AMQPCodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AMQPCodecError::Parse(e) => write!(f, "could not parse frame: {}", e),
+            AMQPCodecError::Generate(e) => write!(f, "could not generate frame: {:?}", e),
+            AMQPCodecError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AMQPCodecError {}
+
+impl From<io::Error> for AMQPCodecError {
+    fn from(err: io::Error) -> Self {
+        AMQPCodecError::Io(err)
+    }
+}
+
+impl Decoder for AMQPCodec {
+    type Item = AMQPFrame;
+    type Error = AMQPCodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<AMQPFrame>, Self::Error> {
+        match parse_frame(src.as_ref()) {
+            Ok((remaining, frame)) => {
+                // Advance the buffer past the bytes we consumed.
+                let consumed = src.len() - remaining.len();
+                src.advance(consumed);
+                Ok(Some(frame))
+            }
+            // Not enough bytes yet: leave the buffer intact and wait for more.
+            Err(nom::Err::Incomplete(_)) => Ok(None),
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                Err(AMQPCodecError::Parse(format!("{:?}", e)))
+            }
+        }
+    }
+}
+
+impl Encoder<AMQPFrame> for AMQPCodec {
+    type Error = AMQPCodecError;
+
+    fn encode(&mut self, frame: AMQPFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let buffer = gen_simple(gen_frame(&frame), Vec::new()).map_err(AMQPCodecError::Generate)?;
+        dst.reserve(buffer.len());
+        dst.put_slice(&buffer);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A heartbeat frame on channel 1, as it appears on the wire.
+    const HEARTBEAT: &[u8] = &[8, 0, 1, 0, 0, 0, 0, 206];
+
+    #[test]
+    fn test_decode() {
+        let mut buffer = BytesMut::from(HEARTBEAT);
+        assert_eq!(
+            AMQPCodec.decode(&mut buffer).unwrap(),
+            Some(AMQPFrame::Heartbeat(1))
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_decode_advances_buffer() {
+        let mut buffer = BytesMut::from(HEARTBEAT);
+        buffer.extend_from_slice(&[42]);
+        assert_eq!(
+            AMQPCodec.decode(&mut buffer).unwrap(),
+            Some(AMQPFrame::Heartbeat(1))
+        );
+        // Only the consumed frame is drained; trailing bytes are left for the next call.
+        assert_eq!(buffer.as_ref(), &[42]);
+    }
+
+    #[test]
+    fn test_decode_incomplete() {
+        let mut buffer = BytesMut::from(&HEARTBEAT[..4]);
+        assert_eq!(AMQPCodec.decode(&mut buffer).unwrap(), None);
+        // The buffer is left intact so more bytes can be appended.
+        assert_eq!(buffer.len(), 4);
+    }
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        let frame = AMQPFrame::Heartbeat(1);
+        let mut buffer = BytesMut::new();
+        AMQPCodec.encode(frame.clone(), &mut buffer).unwrap();
+        assert_eq!(AMQPCodec.decode(&mut buffer).unwrap(), Some(frame));
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{protocol::*, types::*};
+
+mod assembler;
+#[cfg(feature = "codec")]
+mod codec;
+mod parsing;
+
+#[cfg(feature = "codec")]
+pub use self::codec::*;
+pub use self::{assembler::*, parsing::*};
+
+/// The type of an AMQP Frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AMQPFrameType {
+    /// Protocol header
+    ProtocolHeader,
+    /// Method
+    Method,
+    /// Content header
+    Header,
+    /// Content body
+    Body,
+    /// Heartbeat
+    Heartbeat,
+}
+
+/// The different possible frames
+#[derive(Clone, Debug, PartialEq)]
+pub enum AMQPFrame {
+    /// Protocol header frame
+    ProtocolHeader(ProtocolVersion),
+    /// Method frame
+    Method(ShortUInt, AMQPClass),
+    /// Content header frame
+    Header(ShortUInt, Identifier, Box<AMQPContentHeader>),
+    /// Content body frame
+    Body(ShortUInt, Vec<u8>),
+    /// Heartbeat frame
+    Heartbeat(ShortUInt),
+}
+
+/// A raw AMQP frame, whose payload has not yet been parsed
+#[derive(Clone, Debug, PartialEq)]
+pub struct AMQPRawFrame<I> {
+    /// The type of the frame
+    pub frame_type: AMQPFrameType,
+    /// The id of the channel the frame is for
+    pub channel_id: ShortUInt,
+    /// The payload of the frame
+    pub payload: I,
+}
+
+/// A content header, as received before a content body
+#[derive(Clone, Debug, PartialEq)]
+pub struct AMQPContentHeader {
+    /// The class of the content
+    pub class_id: Identifier,
+    /// The weight of the content (reserved, must be 0)
+    pub weight: ShortUInt,
+    /// The size of the content's body
+    pub body_size: LongLongUInt,
+    /// The AMQP properties of the content
+    pub properties: basic::AMQPProperties,
+}
+
+/// A protocol version, as negotiated through the protocol header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProtocolVersion {
+    /// The major version of the protocol
+    pub major: ShortShortUInt,
+    /// The minor version of the protocol
+    pub minor: ShortShortUInt,
+    /// The revision of the protocol
+    pub revision: ShortShortUInt,
+}
+
+impl ProtocolVersion {
+    /// The AMQP 0-9-1 protocol version
+    pub fn amqp_0_9_1() -> ProtocolVersion {
+        ProtocolVersion {
+            major: metadata::MAJOR,
+            minor: metadata::MINOR,
+            revision: metadata::REVISION,
+        }
+    }
+}
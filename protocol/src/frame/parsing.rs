@@ -8,38 +8,111 @@ use crate::{
 use nom::{
     branch::alt,
     bytes::streaming::{tag, take},
-    combinator::{all_consuming, cut, flat_map, map, map_opt, map_res},
+    combinator::{all_consuming, cut, fail, flat_map, map, map_opt, map_res},
     error::context,
     sequence::{pair, tuple},
 };
 use traits::ParsableInput;
 
+/// The number of bytes of framing overhead around a frame payload: the 1-byte
+/// type, the 2-byte channel id, the 4-byte size and the 1-byte frame-end marker.
+const FRAME_OVERHEAD: LongUInt = 8;
+
 /// Parse a channel id
 pub fn parse_channel<I: ParsableInput>(i: I) -> ParserResult<I, AMQPChannel> {
     context("parse_channel", map(parse_id, From::from))(i)
 }
 
+/// Parse the protocol-header wire format into the requested [`ProtocolVersion`]
+///
+/// The AMQP protocol header is the literal `metadata::NAME`, a reserved zero
+/// byte and the three version octets. Shared by [`parse_protocol_header`] and
+/// [`parse_protocol_header_negotiation`] so the wire format is defined once.
+fn parse_protocol_header_version<I: ParsableInput>(i: I) -> ParserResult<I, ProtocolVersion> {
+    map(
+        tuple((
+            tag(metadata::NAME.as_bytes()),
+            tag(&[0][..]),
+            parse_short_short_uint,
+            parse_short_short_uint,
+            parse_short_short_uint,
+        )),
+        |(_, _, major, minor, revision)| ProtocolVersion {
+            major,
+            minor,
+            revision,
+        },
+    )(i)
+}
+
 /// Parse the protocol header
 pub fn parse_protocol_header<I: ParsableInput>(i: I) -> ParserResult<I, ProtocolVersion> {
+    context("parse_protocol_header", parse_protocol_header_version)(i)
+}
+
+/// The outcome of parsing a peer's protocol header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProtocolNegotiation {
+    /// The peer requested the version this build supports
+    Supported(ProtocolVersion),
+    /// The peer requested a version we do not support
+    Mismatch(ProtocolMismatch),
+}
+
+/// A protocol-version mismatch detected during the negotiation handshake.
+///
+/// A server can reply to its peer with the supported protocol header (see
+/// [`serialize_protocol_header`]) instead of just dropping the connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProtocolMismatch {
+    /// The version the peer asked for
+    pub requested: ProtocolVersion,
+    /// The version this build supports
+    pub supported: ProtocolVersion,
+}
+
+/// Parse a protocol header, reporting a version mismatch instead of failing
+///
+/// The peer's requested [`ProtocolVersion`] is parsed even when it does not
+/// match what this build supports, so a server can answer the
+/// version-negotiation handshake rather than fail opaquely.
+pub fn parse_protocol_header_negotiation<I: ParsableInput>(
+    i: I,
+) -> ParserResult<I, ProtocolNegotiation> {
     context(
-        "parse_protocol_header",
-        map(
-            tuple((
-                tag(metadata::NAME.as_bytes()),
-                tag(&[0][..]),
-                parse_short_short_uint,
-                parse_short_short_uint,
-                parse_short_short_uint,
-            )),
-            |(_, _, major, minor, revision)| ProtocolVersion {
-                major,
-                minor,
-                revision,
-            },
-        ),
+        "parse_protocol_header_negotiation",
+        map(parse_protocol_header_version, |requested| {
+            let supported = ProtocolVersion::amqp_0_9_1();
+            if requested == supported {
+                ProtocolNegotiation::Supported(requested)
+            } else {
+                ProtocolNegotiation::Mismatch(ProtocolMismatch {
+                    requested,
+                    supported,
+                })
+            }
+        }),
     )(i)
 }
 
+/// Serialize a [`ProtocolVersion`] back into its `AMQP\0\x00\x09\x01` header form
+///
+/// Used to reply with the supported protocol header when rejecting a peer whose
+/// requested version we do not support.
+pub fn serialize_protocol_header(version: ProtocolVersion) -> [u8; 8] {
+    let name = metadata::NAME.as_bytes();
+    [
+        name[0],
+        name[1],
+        name[2],
+        name[3],
+        0,
+        version.major,
+        version.minor,
+        version.revision,
+    ]
+}
+
 /// Parse the frame type
 pub fn parse_frame_type<I: ParsableInput>(i: I) -> ParserResult<I, AMQPFrameType> {
     context(
@@ -56,11 +129,24 @@ pub fn parse_frame_type<I: ParsableInput>(i: I) -> ParserResult<I, AMQPFrameType
 
 /// Parse a full AMQP Frame (with contents)
 pub fn parse_frame<I: ParsableInput>(i: I) -> ParserResult<I, AMQPFrame> {
+    parse_frame_with(i, parse_raw_frame)
+}
+
+/// Assemble a full AMQP Frame from a raw-frame parser
+///
+/// Shared between [`parse_frame`] and [`parse_frame_max`] so the interpretation
+/// of each frame type lives in a single place regardless of whether a
+/// `frame-max` limit is enforced. The frame-trace hook lives one level down, in
+/// the raw-frame parser, where the declared payload size is still in scope.
+fn parse_frame_with<I: ParsableInput>(
+    i: I,
+    raw: impl Fn(I) -> ParserResult<I, AMQPRawFrame<I>>,
+) -> ParserResult<I, AMQPFrame> {
     context(
         "parse_frame",
         alt((
             map_res(
-                parse_raw_frame,
+                raw,
                 |AMQPRawFrame {
                      channel_id,
                      frame_type,
@@ -82,26 +168,150 @@ pub fn parse_frame<I: ParsableInput>(i: I) -> ParserResult<I, AMQPFrame> {
     )(i)
 }
 
+/// Emit a structured trace record for a raw frame as it comes off the wire.
+///
+/// Logs the frame type, channel id and declared payload size; for Method frames
+/// it additionally resolves the class and method ids from the head of the
+/// payload. Only compiled in when the `frame-trace` feature is enabled; it is a
+/// no-op (and generates no code) otherwise.
+#[cfg(feature = "frame-trace")]
+fn trace_raw_frame<I: ParsableInput>(frame: &AMQPRawFrame<I>, size: LongUInt) {
+    match frame.frame_type {
+        AMQPFrameType::Method => {
+            match pair(parse_id, parse_id)(frame.payload.clone()) {
+                Ok((_, (class_id, method_id))) => log::trace!(
+                    "parsed frame: type=method channel={} size={} class_id={} method_id={}",
+                    frame.channel_id,
+                    size,
+                    class_id,
+                    method_id,
+                ),
+                Err(_) => log::trace!(
+                    "parsed frame: type=method channel={} size={}",
+                    frame.channel_id,
+                    size,
+                ),
+            }
+        }
+        AMQPFrameType::Header => log::trace!(
+            "parsed frame: type=header channel={} size={}",
+            frame.channel_id,
+            size,
+        ),
+        AMQPFrameType::Body => log::trace!(
+            "parsed frame: type=body channel={} size={}",
+            frame.channel_id,
+            size,
+        ),
+        AMQPFrameType::Heartbeat => log::trace!(
+            "parsed frame: type=heartbeat channel={} size={}",
+            frame.channel_id,
+            size,
+        ),
+        AMQPFrameType::ProtocolHeader => {}
+    }
+}
+
+/// Parse a full AMQP Frame, enforcing AMQP 0-9-1 protocol invariants
+///
+/// Unlike [`parse_frame`], which accepts any structurally-valid frame, this
+/// rejects frames that are well-formed but protocol-illegal (a heartbeat or a
+/// connection-class method on a non-zero channel, or a content header carrying a
+/// non-zero weight) with a contextual error naming the broken invariant, so that
+/// a misbehaving peer can be refused at the framing layer.
+///
+/// The remaining AMQP invariant — that the concatenated body length matches the
+/// `body_size` declared in the content header — spans several frames and so
+/// cannot be checked by a single-frame parser; it is enforced by
+/// [`CommandAssembler`](crate::frame::CommandAssembler), which returns
+/// `AssemblyError::BodyTooLong` when a peer overruns the declared size.
+pub fn parse_frame_strict<I: ParsableInput>(i: I) -> ParserResult<I, AMQPFrame> {
+    let (remaining, frame) = parse_frame(i.clone())?;
+    if let Err(invariant) = check_invariants(&frame) {
+        return context(invariant, cut(fail))(i);
+    }
+    Ok((remaining, frame))
+}
+
+/// Check the single-frame AMQP 0-9-1 invariants a strictly-parsed frame must
+/// satisfy, returning the name of the first broken one. The cross-frame
+/// body-size invariant is enforced by the command assembler, not here.
+fn check_invariants(frame: &AMQPFrame) -> Result<(), &'static str> {
+    match frame {
+        AMQPFrame::Heartbeat(channel_id) if *channel_id != 0 => {
+            Err("heartbeat frame on non-zero channel")
+        }
+        AMQPFrame::Method(channel_id, AMQPClass::Connection(_)) if *channel_id != 0 => {
+            Err("connection-class method on non-zero channel")
+        }
+        AMQPFrame::Header(_, _, header) if header.weight != 0 => {
+            Err("content header with non-zero weight")
+        }
+        _ => Ok(()),
+    }
+}
+
 /// Parse a raw AMQP frame
 pub fn parse_raw_frame<I: ParsableInput>(i: I) -> ParserResult<I, AMQPRawFrame<I>> {
-    context(
-        "parse_raw_frame",
-        flat_map(parse_frame_type, move |frame_type| {
-            cut(flat_map(
-                pair(parse_id, parse_long_uint),
-                move |(channel_id, size)| {
-                    map(
-                        pair(take(size), tag(&[constants::FRAME_END][..])),
-                        move |(payload, _)| AMQPRawFrame {
-                            frame_type,
-                            channel_id,
-                            payload,
-                        },
-                    )
-                },
-            ))
-        }),
-    )(i)
+    // A `frame_max` of 0 means no limit, so this is the unbounded variant.
+    parse_raw_frame_max(0)(i)
+}
+
+/// Parse a full AMQP Frame (with contents), rejecting frames larger than `frame_max`
+///
+/// This mirrors [`parse_frame`] but enforces the `frame-max` negotiated during
+/// `connection.tune`. A `frame_max` of `0` means no limit, as per the spec.
+pub fn parse_frame_max<I: ParsableInput>(
+    i: I,
+    frame_max: LongUInt,
+) -> ParserResult<I, AMQPFrame> {
+    parse_frame_with(i, parse_raw_frame_max(frame_max))
+}
+
+/// Parse a raw AMQP frame, rejecting frames whose declared size exceeds `frame_max`
+///
+/// The size check happens before the payload is taken, so an oversized frame
+/// fails fast with a contextual error instead of asking for more data through
+/// `Incomplete` and letting a peer make us buffer up to 4 GiB per frame. The
+/// `frame-max` limit bounds the whole frame, so the 8 bytes of framing overhead
+/// (1 type + 2 channel + 4 size + 1 frame-end) are accounted for. A `frame_max`
+/// of `0` disables the check.
+pub fn parse_raw_frame_max<I: ParsableInput>(
+    frame_max: LongUInt,
+) -> impl Fn(I) -> ParserResult<I, AMQPRawFrame<I>> {
+    move |i: I| {
+        context(
+            "parse_raw_frame",
+            flat_map(parse_frame_type, move |frame_type| {
+                cut(flat_map(
+                    pair(parse_id, parse_long_uint),
+                    move |(channel_id, size)| {
+                        move |input: I| {
+                            // The negotiated frame-max bounds the entire frame, payload plus the
+                            // 8 bytes of framing overhead, so compare against that total.
+                            if frame_max != 0 && size.saturating_add(FRAME_OVERHEAD) > frame_max {
+                                context("frame size exceeds negotiated frame_max", fail)(input)
+                            } else {
+                                map(
+                                    pair(take(size), tag(&[constants::FRAME_END][..])),
+                                    move |(payload, _)| {
+                                        let frame = AMQPRawFrame {
+                                            frame_type,
+                                            channel_id,
+                                            payload,
+                                        };
+                                        #[cfg(feature = "frame-trace")]
+                                        trace_raw_frame(&frame, size);
+                                        frame
+                                    },
+                                )(input)
+                            }
+                        }
+                    },
+                ))
+            }),
+        )(i)
+    }
 }
 
 /// Parse a content header frame
@@ -147,4 +357,45 @@ mod test {
             Ok((&[][..], AMQPFrame::Heartbeat(1)))
         );
     }
+
+    #[test]
+    fn test_protocol_header_negotiation_supported() {
+        assert_eq!(
+            parse_protocol_header_negotiation(
+                &['A' as u8, 'M' as u8, 'Q' as u8, 'P' as u8, 0, 0, 9, 1][..]
+            ),
+            Ok((
+                &[][..],
+                ProtocolNegotiation::Supported(ProtocolVersion::amqp_0_9_1())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_protocol_header_negotiation_mismatch() {
+        assert_eq!(
+            parse_protocol_header_negotiation(
+                &['A' as u8, 'M' as u8, 'Q' as u8, 'P' as u8, 0, 1, 0, 0][..]
+            ),
+            Ok((
+                &[][..],
+                ProtocolNegotiation::Mismatch(ProtocolMismatch {
+                    requested: ProtocolVersion {
+                        major: 1,
+                        minor: 0,
+                        revision: 0,
+                    },
+                    supported: ProtocolVersion::amqp_0_9_1(),
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn test_serialize_protocol_header() {
+        assert_eq!(
+            serialize_protocol_header(ProtocolVersion::amqp_0_9_1()),
+            [b'A', b'M', b'Q', b'P', 0, 0, 9, 1]
+        );
+    }
 }